@@ -0,0 +1,362 @@
+//! Precedence-climbing (Pratt) expression parser over the `TokenTree` output
+//! of the lexer, mirroring rustc_parse's `expr.rs` and its operator
+//! precedence table.
+
+use crate::{Delim, IntegerKind, Operator, Span, Spanned, Token, TokenTree};
+use std::ops::BitOr;
+
+/// Contextual restrictions on how an expression may be parsed, ported from
+/// rustc_parse's `Restrictions`. These are scoped around a sub-parse with
+/// [`with_res`] rather than being a global setting, so e.g. parsing inside a
+/// pair of parentheses can freely lift restrictions imposed by whatever
+/// encloses them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Restrictions(u8);
+
+impl Restrictions {
+    const NONE: Restrictions = Restrictions(0);
+    /// Set while parsing the scrutinee of a condition. The parser has no
+    /// record/struct literal syntax and no block expression, and nothing
+    /// reads this flag, so it does not change what is accepted.
+    const NO_STRUCT_LITERAL: Restrictions = Restrictions(1 << 0);
+    /// Set while parsing an expression in statement position. Nothing reads
+    /// this flag: a leading `Delim::Block` tree is rejected with
+    /// `ExprError::ExpectedAtom` here as everywhere else.
+    const STMT_EXPR: Restrictions = Restrictions(1 << 1);
+
+    fn contains(self, other: Restrictions) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Restrictions {
+    type Output = Restrictions;
+
+    fn bitor(self, rhs: Restrictions) -> Restrictions {
+        Restrictions(self.0 | rhs.0)
+    }
+}
+
+/// Runs `f` with the cursor's restrictions temporarily replaced by `flags`,
+/// restoring whatever was set before once `f` returns, matching how rustc
+/// scopes restrictions during recursive descent.
+fn with_res<T>(
+    cursor: &mut Cursor,
+    flags: Restrictions,
+    f: impl FnOnce(&mut Cursor) -> Result<T, ExprError>,
+) -> Result<T, ExprError> {
+    let prev = cursor.restrictions;
+    cursor.restrictions = flags;
+    let result = f(cursor);
+    cursor.restrictions = prev;
+    result
+}
+
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Ident(String),
+    Int(u64, Option<IntegerKind>),
+    String(String),
+    Binary(Operator, Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+}
+
+#[derive(Clone, Debug)]
+pub enum ExprError {
+    /// Expected an identifier, integer or string literal, or parenthesized
+    /// expression here.
+    ExpectedAtom(Span),
+    /// An operator had nothing following it to act as its right-hand operand.
+    MissingRhs { op: Operator, op_span: Span },
+    /// A string literal contained a `${...}` interpolation, which `Expr`
+    /// doesn't have a representation for yet.
+    UnsupportedStringInterpolation(Span),
+}
+
+/// Comments carry no meaning for the expression grammar: the lexer keeps
+/// plain comments only to preserve semantic indentation, and doc comments
+/// for a later item stage. Drop both kinds (and any inside nested trees)
+/// before parsing.
+fn strip_comments(tokens: &[Spanned<TokenTree>]) -> Vec<Spanned<TokenTree>> {
+    tokens
+        .iter()
+        .filter(|(tt, _)| {
+            !matches!(
+                tt,
+                TokenTree::Token(Token::Comment, _) | TokenTree::Token(Token::DocComment { .. }, _)
+            )
+        })
+        .map(|(tt, span)| {
+            let tt = match tt {
+                TokenTree::Tree(delim, inner) => TokenTree::Tree(delim.clone(), strip_comments(inner)),
+                tt => tt.clone(),
+            };
+            (tt, span.clone())
+        })
+        .collect()
+}
+
+/// Binding power of an infix operator: how tightly it binds on its left and
+/// right. Higher binds tighter; a right higher than left (as with `Add`) is
+/// left-associative, and a right lower than left (as with `Equals`) is
+/// right-associative.
+fn binding_power(op: &Operator) -> (u8, u8) {
+    match op {
+        Operator::MemberAccess => (90, 91),
+        Operator::Add => (50, 51),
+        Operator::Range => (40, 41),
+        Operator::Equality => (30, 31),
+        Operator::Equals => (21, 20),
+        Operator::AddAssign => (21, 20),
+    }
+}
+
+struct Cursor<'a> {
+    tokens: &'a [Spanned<TokenTree>],
+    pos: usize,
+    restrictions: Restrictions,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<&'a Spanned<TokenTree>> {
+        self.tokens.get(self.pos)
+    }
+
+    fn bump(&mut self) -> Option<&'a Spanned<TokenTree>> {
+        let tok = self.tokens.get(self.pos);
+        if tok.is_some() {
+            self.pos += 1;
+        }
+        tok
+    }
+
+    /// An empty span at the end of the token slice, used when an error has
+    /// nothing left to point at.
+    fn end_span(&self) -> Span {
+        match self.tokens.last() {
+            Some((_, span)) => span.end..span.end,
+            None => 0..0,
+        }
+    }
+}
+
+fn peek_operator(cursor: &Cursor) -> Option<(Operator, Span)> {
+    match cursor.peek() {
+        Some((TokenTree::Token(Token::Operator(op), _), span)) => Some((op.clone(), span.clone())),
+        _ => None,
+    }
+}
+
+fn parse_atom(cursor: &mut Cursor) -> Result<Spanned<Expr>, ExprError> {
+    match cursor.bump() {
+        Some((TokenTree::Token(Token::Ident(name), _), span)) => {
+            Ok((Expr::Ident(name.clone()), span.clone()))
+        }
+        Some((TokenTree::Token(Token::Int(value, kind), _), span)) => {
+            Ok((Expr::Int(*value, kind.clone()), span.clone()))
+        }
+        Some((TokenTree::Token(Token::StringLiteral(s), _), span)) => {
+            Ok((Expr::String(s.clone()), span.clone()))
+        }
+        Some((TokenTree::Tree(Delim::Interpolation, parts), span)) => {
+            let mut text = String::new();
+            for (part, part_span) in parts {
+                match part {
+                    TokenTree::Token(Token::StringLiteral(s), _) => text.push_str(s),
+                    // `${...}` interpolation: there's no `Expr` variant to
+                    // splice the embedded expression into yet.
+                    TokenTree::Tree(Delim::Curly, _) => {
+                        return Err(ExprError::UnsupportedStringInterpolation(part_span.clone()));
+                    }
+                    _ => return Err(ExprError::ExpectedAtom(part_span.clone())),
+                }
+            }
+            Ok((Expr::String(text), span.clone()))
+        }
+        Some((TokenTree::Tree(Delim::Parenthesis, inner), span)) => {
+            // Restrictions imposed by whatever encloses this expression don't
+            // apply once we're inside our own pair of parentheses.
+            let (expr, _) = parse_nested(inner, Restrictions::NONE)?;
+            Ok((expr, span.clone()))
+        }
+        Some((_, span)) => Err(ExprError::ExpectedAtom(span.clone())),
+        None => Err(ExprError::ExpectedAtom(cursor.end_span())),
+    }
+}
+
+fn parse_expr_bp(cursor: &mut Cursor, min_bp: u8) -> Result<Spanned<Expr>, ExprError> {
+    let mut lhs = parse_atom(cursor)?;
+
+    loop {
+        let (op, op_span) = match peek_operator(cursor) {
+            Some(op) => op,
+            None => break,
+        };
+        let (left_bp, right_bp) = binding_power(&op);
+        if left_bp < min_bp {
+            break;
+        }
+        cursor.bump();
+
+        // Only replace the rhs parse's error with `MissingRhs` when there's
+        // truly nothing left to parse; if an operand was there but failed
+        // for some other reason (e.g. unsupported string interpolation),
+        // that error is more useful than a generic "missing operand" and
+        // should reach the caller as-is.
+        if cursor.peek().is_none() {
+            return Err(ExprError::MissingRhs { op, op_span });
+        }
+        // The right-hand operand inherits the restrictions currently in
+        // effect; pushed and popped explicitly so a future operator that
+        // needs to change them (as rustc's assignment-expression parsing
+        // does) has a scope to hook into.
+        let restrictions = cursor.restrictions;
+        let rhs = with_res(cursor, restrictions, |cursor| parse_expr_bp(cursor, right_bp))?;
+
+        let span = lhs.1.start..rhs.1.end;
+        lhs = (Expr::Binary(op, Box::new(lhs), Box::new(rhs)), span);
+    }
+
+    Ok(lhs)
+}
+
+fn parse_nested(tokens: &[Spanned<TokenTree>], restrictions: Restrictions) -> Result<Spanned<Expr>, ExprError> {
+    let tokens = strip_comments(tokens);
+    let mut cursor = Cursor { tokens: &tokens, pos: 0, restrictions };
+    parse_expr_bp(&mut cursor, 0)
+}
+
+/// Parse a full expression out of a flattened token-tree slice, e.g. the
+/// contents of a `Delim::Block` or `Delim::Parenthesis` tree.
+pub fn parse_expr(tokens: &[Spanned<TokenTree>]) -> Result<Spanned<Expr>, ExprError> {
+    parse_nested(tokens, Restrictions::NONE)
+}
+
+/// Parse the scrutinee of a condition, with `Restrictions::NO_STRUCT_LITERAL`
+/// in effect. Nothing reads that flag, so this accepts exactly what
+/// [`parse_expr`] accepts.
+pub fn parse_condition(tokens: &[Spanned<TokenTree>]) -> Result<Spanned<Expr>, ExprError> {
+    parse_nested(tokens, Restrictions::NO_STRUCT_LITERAL)
+}
+
+/// Parse an expression appearing in statement position, with
+/// `Restrictions::STMT_EXPR` in effect. Nothing reads that flag, so this
+/// accepts exactly what [`parse_expr`] accepts; a leading `Delim::Block` tree
+/// is an [`ExprError::ExpectedAtom`] error.
+pub fn parse_stmt_expr(tokens: &[Spanned<TokenTree>]) -> Result<Spanned<Expr>, ExprError> {
+    parse_nested(tokens, Restrictions::STMT_EXPR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Spacing;
+
+    fn tok(t: Token) -> Spanned<TokenTree> {
+        (TokenTree::Token(t, Spacing::Alone), 0..0)
+    }
+
+    fn ident(name: &str) -> Spanned<TokenTree> {
+        tok(Token::Ident(name.to_string()))
+    }
+
+    #[test]
+    fn chained_equality_is_left_associative() {
+        // `a == b == c` should parse as `(a == b) == c`, like `Add` and
+        // every other non-assignment operator in the table.
+        let tokens = vec![
+            ident("a"),
+            tok(Token::Operator(Operator::Equality)),
+            ident("b"),
+            tok(Token::Operator(Operator::Equality)),
+            ident("c"),
+        ];
+        let (expr, _) = parse_expr(&tokens).expect("should parse");
+        match expr {
+            Expr::Binary(Operator::Equality, lhs, _) => {
+                assert!(matches!(lhs.0, Expr::Binary(Operator::Equality, ..)));
+            }
+            other => panic!("expected a top-level Equality binary, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn comment_between_operator_and_lhs_is_skipped() {
+        let tokens = vec![ident("a"), tok(Token::Comment), tok(Token::Operator(Operator::Add)), ident("b")];
+        let (expr, _) = parse_expr(&tokens).expect("should parse past the comment");
+        assert!(matches!(expr, Expr::Binary(Operator::Add, ..)));
+    }
+
+    #[test]
+    fn comment_between_operator_and_rhs_is_skipped() {
+        let tokens = vec![
+            ident("a"),
+            tok(Token::Operator(Operator::Add)),
+            tok(Token::DocComment {
+                style: crate::DocStyle::Outer,
+                text: "not a real rhs".to_string(),
+            }),
+            ident("b"),
+        ];
+        let (expr, _) = parse_expr(&tokens).expect("should parse past the doc comment");
+        assert!(matches!(expr, Expr::Binary(Operator::Add, ..)));
+    }
+
+    #[test]
+    fn string_literal_atom_parses_from_the_interpolation_tree_the_lexer_emits() {
+        let tokens = vec![(
+            TokenTree::Tree(
+                Delim::Interpolation,
+                vec![tok(Token::StringLiteral("hello".to_string()))],
+            ),
+            0..0,
+        )];
+        let (expr, _) = parse_expr(&tokens).expect("a plain string literal should parse");
+        assert!(matches!(expr, Expr::String(s) if s == "hello"));
+    }
+
+    #[test]
+    fn string_interpolation_is_not_yet_supported() {
+        let tokens = vec![(
+            TokenTree::Tree(
+                Delim::Interpolation,
+                vec![(TokenTree::Tree(Delim::Curly, vec![ident("x")]), 0..0)],
+            ),
+            0..0,
+        )];
+        assert!(matches!(
+            parse_expr(&tokens),
+            Err(ExprError::UnsupportedStringInterpolation(_))
+        ));
+    }
+
+    #[test]
+    fn rhs_error_is_not_masked_as_a_generic_missing_rhs() {
+        // `a + "${x}"`: the rhs is present but fails to parse for its own
+        // reason, which should reach the caller instead of being papered
+        // over with a generic "nothing was there" error.
+        let tokens = vec![
+            ident("a"),
+            tok(Token::Operator(Operator::Add)),
+            (
+                TokenTree::Tree(
+                    Delim::Interpolation,
+                    vec![(TokenTree::Tree(Delim::Curly, vec![ident("x")]), 0..0)],
+                ),
+                0..0,
+            ),
+        ];
+        assert!(matches!(
+            parse_expr(&tokens),
+            Err(ExprError::UnsupportedStringInterpolation(_))
+        ));
+    }
+
+    #[test]
+    fn truly_missing_rhs_still_reports_missing_rhs() {
+        let tokens = vec![ident("a"), tok(Token::Operator(Operator::Add))];
+        assert!(matches!(
+            parse_expr(&tokens),
+            Err(ExprError::MissingRhs { op: Operator::Add, .. })
+        ));
+    }
+}
@@ -1,7 +1,11 @@
 use chumsky::{prelude::*, text::Character};
+use std::cell::RefCell;
 use std::ops::Range;
+use std::rc::Rc;
 
-#[derive(Clone, Debug)]
+mod expr;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 enum Delim {
     Block,
     Parenthesis,
@@ -23,11 +27,31 @@ enum IntegerKind {
     Usz,
 }
 
+#[derive(Clone, Debug)]
+enum FloatKind {
+    F32,
+    F64,
+}
+
 #[derive(Clone, Debug)]
 enum Operator {
     Add,
     Equals,
     MemberAccess,
+    /// `==`, glued from two `Equals` operators joined by `Spacing::Joint`.
+    Equality,
+    /// `+=`, glued from `Add` followed by `Equals`.
+    AddAssign,
+    /// `..`, glued from two `MemberAccess` operators.
+    Range,
+}
+
+/// Whether a doc comment documents the item following it (`///`, `/** */`)
+/// or the item enclosing it (`//!`, `/*! */`).
+#[derive(Clone, Debug)]
+enum DocStyle {
+    Outer,
+    Inner,
 }
 
 #[derive(Clone, Debug)]
@@ -35,15 +59,66 @@ enum Token {
     Ident(String),
     StringLiteral(String),
     Int(u64, Option<IntegerKind>),
+    Float(f64, Option<FloatKind>),
     Operator(Operator),
     Comment,
+    DocComment { style: DocStyle, text: String },
     Open(Delim),
     Close(Delim),
 }
 
+/// Removes `_` digit separators so the remaining digits can be fed to
+/// `from_str`/`from_str_radix`.
+fn strip_digit_separators(s: &str) -> String {
+    s.chars().filter(|c| *c != '_').collect()
+}
+
+/// The two shapes a numeral can take before a suffix is attached.
+enum NumKind {
+    Int(u64),
+    Float(f64),
+}
+
+/// Strips a single conventional leading space from a line doc comment's
+/// captured text, e.g. the text of `/// hello` is `hello`, not ` hello`.
+fn strip_line_doc_decoration(text: &str) -> String {
+    text.strip_prefix(' ').unwrap_or(text).to_string()
+}
+
+/// Strips the leading `*` (and the space after it) that conventionally
+/// decorates every continuation line of a block doc comment, along with a
+/// single leading space on the first line, following rustc's
+/// `strip_doc_comment_decoration`.
+fn strip_block_doc_decoration(text: &str) -> String {
+    let mut lines = text.lines();
+    let mut out = lines
+        .next()
+        .map(|first| first.strip_prefix(' ').unwrap_or(first).to_string())
+        .unwrap_or_default();
+    for line in lines {
+        out.push('\n');
+        let trimmed = line.trim_start();
+        let trimmed = trimmed.strip_prefix('*').unwrap_or(trimmed);
+        let trimmed = trimmed.strip_prefix(' ').unwrap_or(trimmed);
+        out.push_str(trimmed);
+    }
+    out
+}
+
+/// Whether a token touches the one that follows it, with no intervening
+/// whitespace. Mirrors rustc's tokenstream `Spacing`: `Joint` tokens can be
+/// glued back together by a later stage to recover multi-character operators
+/// like `==` or `+=` out of the single-char `Operator` tokens lexed here,
+/// while `Alone` tokens are guaranteed to stand on their own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Spacing {
+    Joint,
+    Alone,
+}
+
 #[derive(Clone, Debug)]
 enum TokenTree {
-    Token(Token),
+    Token(Token, Spacing),
     Tree(Delim, Vec<Spanned<TokenTree>>),
 }
 
@@ -51,7 +126,80 @@ type Span = Range<usize>;
 
 type Spanned<T> = (T, Span);
 
-fn lexer() -> impl Parser<char, Vec<Spanned<TokenTree>>, Error = Simple<char>> {
+/// A delimiter that was opened but never (or wrongly) closed, or a close
+/// with nothing open for it to match at all, discovered while matching
+/// `(...)` and `${...}` pairs. Carries both ends of the mismatch so a
+/// diagnostic can point at the opener and, if one was found, the offending
+/// closer, mirroring rustc_parse's `UnmatchedDelim`.
+#[derive(Clone, Debug)]
+struct DelimError {
+    delim: Delim,
+    /// Where this delimiter was opened, or `None` for a stray close with no
+    /// enclosing group open at all.
+    open: Option<Span>,
+    /// The span of the closing delimiter that was found in place of the
+    /// expected one, if the mismatch was a wrong close rather than EOF.
+    unexpected_close: Option<Span>,
+}
+
+/// Which compound operator two adjacent single-character operators spell
+/// out, if any. Only covers pairs this lexer can already produce on their
+/// own; `->` is left for whenever `-` and `>` are themselves lexed, since
+/// there'd be nothing to glue them from yet.
+fn glue_operator(first: &Operator, second: &Operator) -> Option<Operator> {
+    match (first, second) {
+        (Operator::Equals, Operator::Equals) => Some(Operator::Equality),
+        (Operator::Add, Operator::Equals) => Some(Operator::AddAssign),
+        (Operator::MemberAccess, Operator::MemberAccess) => Some(Operator::Range),
+        _ => None,
+    }
+}
+
+/// Glues adjacent single-character operator tokens back into the
+/// multi-character operators they spell out, the way rustc's tokenstream
+/// glues `Joint` tokens before the parser ever sees them. `Spacing::Joint`
+/// is what lets this tell `==` apart from `= =` with a space in between.
+/// Recurses into nested trees so operators glue the same way regardless of
+/// how deep in the tree they are.
+fn glue_operators(tts: Vec<Spanned<TokenTree>>) -> Vec<Spanned<TokenTree>> {
+    let mut glued = Vec::with_capacity(tts.len());
+    let mut iter = tts.into_iter();
+    let mut pending: Option<Spanned<TokenTree>> = None;
+    while let Some((tt, span)) = pending.take().or_else(|| iter.next()) {
+        let tt = match tt {
+            TokenTree::Tree(delim, inner) => TokenTree::Tree(delim, glue_operators(inner)),
+            tt => tt,
+        };
+        if let TokenTree::Token(Token::Operator(op), Spacing::Joint) = &tt {
+            if let Some((next_tt, next_span)) = iter.next() {
+                if let TokenTree::Token(Token::Operator(next_op), next_spacing) = &next_tt {
+                    if let Some(glued_op) = glue_operator(op, next_op) {
+                        glued.push((
+                            TokenTree::Token(Token::Operator(glued_op), *next_spacing),
+                            span.start..next_span.end,
+                        ));
+                        continue;
+                    }
+                }
+                glued.push((tt, span));
+                pending = Some((next_tt, next_span));
+                continue;
+            }
+        }
+        glued.push((tt, span));
+    }
+    glued
+}
+
+fn lexer(
+    delim_errors: Rc<RefCell<Vec<DelimError>>>,
+) -> impl Parser<char, Vec<Spanned<TokenTree>>, Error = Simple<char>> {
+    // A closing delimiter character on its own, with no parsed-successfully
+    // structure around it yet. Used both to recover an `(...)`/`${...}`
+    // group whose own close didn't match (below), and, with nothing
+    // enclosing it at all, as a top-level orphan close.
+    let stray_close = filter(|c: &char| *c == ')' || *c == '}').map_with_span(|c, span: Span| (c, span));
+
     let tt = recursive(|tt| {
         let operator = just('+')
             .to(Operator::Add)
@@ -62,26 +210,124 @@ fn lexer() -> impl Parser<char, Vec<Spanned<TokenTree>>, Error = Simple<char>> {
 
         let line_ws = filter(|c: &char| c.is_inline_whitespace()).repeated();
 
+        // A token is `Joint` when the very next character is neither
+        // whitespace nor end of input, i.e. nothing separates it from
+        // whatever comes next.
+        let spacing = filter(|c: &char| !c.is_inline_whitespace() && *c != '\n' && *c != '\r')
+            .rewind()
+            .to(Spacing::Joint)
+            .or_not()
+            .map(|spacing| spacing.unwrap_or(Spacing::Alone));
+
         let ident = text::ident().map(Token::Ident).labelled("Identifier");
-        let int = text::int(10)
-            .from_str()
-            .unwrapped()
+
+        let int_suffix = text::keyword("i8")
+            .to(IntegerKind::I8)
+            .or(text::keyword("i16").to(IntegerKind::I16))
+            .or(text::keyword("i32").to(IntegerKind::I32))
+            .or(text::keyword("i64").to(IntegerKind::I64))
+            .or(text::keyword("isz").to(IntegerKind::Isz))
+            .or(text::keyword("u8").to(IntegerKind::U8))
+            .or(text::keyword("u16").to(IntegerKind::U16))
+            .or(text::keyword("u32").to(IntegerKind::U32))
+            .or(text::keyword("u64").to(IntegerKind::U64))
+            .or(text::keyword("usz").to(IntegerKind::Usz));
+        let float_suffix = text::keyword("f32")
+            .to(FloatKind::F32)
+            .or(text::keyword("f64").to(FloatKind::F64));
+
+        // Digits for a given radix, with `_` separators allowed anywhere
+        // between them; they're stripped before the digits reach `from_str`.
+        let hex_digits = filter(|c: &char| c.is_ascii_hexdigit() || *c == '_')
+            .repeated()
+            .at_least(1)
+            .collect::<String>();
+        let oct_digits = filter(|c: &char| c.is_digit(8) || *c == '_')
+            .repeated()
+            .at_least(1)
+            .collect::<String>();
+        let bin_digits = filter(|c: &char| c.is_digit(2) || *c == '_')
+            .repeated()
+            .at_least(1)
+            .collect::<String>();
+        // Unlike the radix-prefixed digit groups above, a bare decimal number
+        // must start with an actual digit rather than a separator: `0x_1` is
+        // unambiguous, but a leading `_` here is indistinguishable from an
+        // identifier (`_123`, or the wildcard `_` itself) until we commit to
+        // one or the other.
+        let dec_digits = filter(|c: &char| c.is_ascii_digit())
             .then(
-                text::keyword("i8")
-                    .to(IntegerKind::I8)
-                    .or(text::keyword("i16").to(IntegerKind::I16))
-                    .or(text::keyword("i32").to(IntegerKind::I32))
-                    .or(text::keyword("i64").to(IntegerKind::I64))
-                    .or(text::keyword("isz").to(IntegerKind::Isz))
-                    .or(text::keyword("u8").to(IntegerKind::U8))
-                    .or(text::keyword("u16").to(IntegerKind::U16))
-                    .or(text::keyword("u32").to(IntegerKind::U32))
-                    .or(text::keyword("u64").to(IntegerKind::U64))
-                    .or(text::keyword("usz").to(IntegerKind::Usz))
-                    .or_not(),
+                filter(|c: &char| c.is_ascii_digit() || *c == '_')
+                    .repeated()
+                    .collect::<String>(),
             )
-            .map(|(i, t)| Token::Int(i, t))
-            .labelled("Integer");
+            .map(|(first, rest)| format!("{first}{rest}"));
+
+        let radix_int = just("0x")
+            .ignore_then(hex_digits)
+            .map(|digits| (digits, 16))
+            .or(just("0o").ignore_then(oct_digits).map(|digits| (digits, 8)))
+            .or(just("0b").ignore_then(bin_digits).map(|digits| (digits, 2)))
+            .try_map(|(digits, radix), span| {
+                u64::from_str_radix(&strip_digit_separators(&digits), radix)
+                    .map(NumKind::Int)
+                    .map_err(|_| Simple::custom(span, "integer literal overflows u64"))
+            });
+
+        // A `.` only introduces a fractional part when followed by a digit;
+        // otherwise it's left alone so `MemberAccess` can parse it, letting
+        // `x.0` and `1.method()` lex as expected instead of swallowing the
+        // `.` into a float.
+        let fraction = just('.')
+            .then_ignore(filter(|c: &char| c.is_ascii_digit()).rewind())
+            .ignore_then(dec_digits);
+        let exponent = just('e')
+            .or(just('E'))
+            .ignore_then(just('+').or(just('-')).or_not())
+            .then(dec_digits)
+            .map(|(sign, digits)| match sign {
+                Some(sign) => format!("{sign}{digits}"),
+                None => digits,
+            });
+
+        let decimal_num = dec_digits
+            .then(fraction.or_not())
+            .then(exponent.or_not())
+            .try_map(|((int_part, frac_part), exp_part), span| {
+                if frac_part.is_none() && exp_part.is_none() {
+                    return strip_digit_separators(&int_part)
+                        .parse()
+                        .map(NumKind::Int)
+                        .map_err(|_| Simple::custom(span, "integer literal overflows u64"));
+                }
+
+                let mut text = strip_digit_separators(&int_part);
+                text.push('.');
+                text.push_str(&strip_digit_separators(frac_part.as_deref().unwrap_or("0")));
+                if let Some(exp) = &exp_part {
+                    text.push('e');
+                    text.push_str(&strip_digit_separators(exp));
+                }
+                text.parse()
+                    .map(NumKind::Float)
+                    .map_err(|_| Simple::custom(span, "invalid float literal"))
+            });
+
+        let int = radix_int
+            .or(decimal_num)
+            .then(int_suffix.or_not())
+            .then(float_suffix.or_not())
+            .try_map(|((num, int_kind), float_kind), span| match (num, int_kind, float_kind) {
+                (NumKind::Float(_), Some(_), _) | (_, Some(_), Some(_)) => Err(Simple::custom(
+                    span,
+                    "numeric literal cannot have both an integer and a float suffix",
+                )),
+                (NumKind::Int(v), Some(kind), None) => Ok(Token::Int(v, Some(kind))),
+                (NumKind::Int(v), None, None) => Ok(Token::Int(v, None)),
+                (NumKind::Int(v), None, Some(kind)) => Ok(Token::Float(v as f64, Some(kind))),
+                (NumKind::Float(v), None, kind) => Ok(Token::Float(v, kind)),
+            })
+            .labelled("Number");
 
         let escape = just('\\').ignore_then(
             just('\\')
@@ -100,15 +346,39 @@ fn lexer() -> impl Parser<char, Vec<Spanned<TokenTree>>, Error = Simple<char>> {
             .at_least(1)
             .collect()
             .map(Token::StringLiteral)
-            .map_with_span(|s, span| (TokenTree::Token(s), span))
+            // String contents are consumed char-by-char with no whitespace
+            // skipping in between, so `Spacing` carries no useful information
+            // here; `Alone` is the inert choice.
+            .map_with_span(|s, span| (TokenTree::Token(s, Spacing::Alone), span))
             .labelled("String literal");
 
-        let interpolation = tt
-            .clone()
-            .padded()
-            .repeated()
-            .delimited_by(just("${"), just('}'))
-            .map_with_span(|tts, span| (TokenTree::Tree(Delim::Curly, tts), span));
+        let curly_errors = delim_errors.clone();
+        let interpolation = just("${")
+            .map_with_span(|_, span: Span| span)
+            .then(tt.clone().padded().repeated())
+            .then(
+                // A close is either the delimiter this group actually
+                // expects, or (if that's not what's there) some other
+                // stray close; these have to be tried as alternatives
+                // rather than two separate `.then`s, since trying the
+                // stray-close branch after a successful proper close
+                // would just eat whatever close comes after this group.
+                just('}')
+                    .map_with_span(|_, span: Span| (Some(span), None))
+                    .or(stray_close.map(|(_, span)| (None, Some(span))))
+                    .or_not(),
+            )
+            .map_with_span(move |((open, tts), close_or_stray), span| {
+                let (close, stray) = close_or_stray.unwrap_or((None, None));
+                if close.is_none() {
+                    curly_errors.borrow_mut().push(DelimError {
+                        delim: Delim::Curly,
+                        open: Some(open),
+                        unexpected_close: stray,
+                    });
+                }
+                (TokenTree::Tree(Delim::Curly, tts), span)
+            });
 
         let string = interpolation
             .or(literal)
@@ -131,25 +401,92 @@ fn lexer() -> impl Parser<char, Vec<Spanned<TokenTree>>, Error = Simple<char>> {
             .then_ignore(line_ws.then(single).not().rewind())
             .labelled("Final token in a sequence");
 
-        let single = sequential.or(last).or(operator).map(TokenTree::Token);
+        let single = sequential
+            .or(last)
+            .or(operator)
+            .then(spacing)
+            .map(|(tok, spacing)| TokenTree::Token(tok, spacing));
 
-        let token_tree = tt
-            .padded()
-            .repeated()
-            .delimited_by(just('('), just(')'))
-            .map(|tts| TokenTree::Tree(Delim::Parenthesis, tts));
+        // Like `interpolation` above: track the opener's span and, on a missing
+        // or mismatched close, record a `DelimError` instead of failing the
+        // whole lex. The synthesized `Tree` lets lexing carry on as though the
+        // expected close had been found: a missing close ends the group where
+        // the contents stop, and a mismatched close is consumed in its place.
+        let paren_errors = delim_errors.clone();
+        let token_tree = just('(')
+            .map_with_span(|_, span: Span| span)
+            .then(tt.padded().repeated())
+            .then(
+                // See `interpolation` above: tried as alternatives so a
+                // successful proper close doesn't fall through into also
+                // consuming whatever close follows this group.
+                just(')')
+                    .map_with_span(|_, span: Span| (Some(span), None))
+                    .or(stray_close.map(|(_, span)| (None, Some(span))))
+                    .or_not(),
+            )
+            .map(move |((open, tts), close_or_stray)| {
+                let (close, stray) = close_or_stray.unwrap_or((None, None));
+                if close.is_none() {
+                    paren_errors.borrow_mut().push(DelimError {
+                        delim: Delim::Parenthesis,
+                        open: Some(open),
+                        unexpected_close: stray,
+                    });
+                }
+                TokenTree::Tree(Delim::Parenthesis, tts)
+            });
 
+        // `////` and beyond, and `/**/`/`/***` and beyond, fall back to plain
+        // comments rather than doc comments, matching rustc's convention.
+        let outer_line_doc = just("///")
+            .then_ignore(just('/').not().rewind())
+            .ignore_then(take_until(text::newline().rewind()))
+            .map(|(chars, _): (Vec<char>, ())| Token::DocComment {
+                style: DocStyle::Outer,
+                text: strip_line_doc_decoration(&chars.into_iter().collect::<String>()),
+            });
+        let inner_line_doc = just("//!")
+            .ignore_then(take_until(text::newline().rewind()))
+            .map(|(chars, _): (Vec<char>, ())| Token::DocComment {
+                style: DocStyle::Inner,
+                text: strip_line_doc_decoration(&chars.into_iter().collect::<String>()),
+            });
         let single_line = just("//")
             .then(take_until(text::newline().rewind()))
             .ignored()
             .to(Token::Comment);
+
+        let outer_block_doc = just("/**")
+            .then_ignore(just('*').not().rewind())
+            .then_ignore(just('/').not().rewind())
+            .ignore_then(take_until(just("*/")))
+            .map(|(chars, _): (Vec<char>, &str)| Token::DocComment {
+                style: DocStyle::Outer,
+                text: strip_block_doc_decoration(&chars.into_iter().collect::<String>()),
+            });
+        let inner_block_doc = just("/*!")
+            .ignore_then(take_until(just("*/")))
+            .map(|(chars, _): (Vec<char>, &str)| Token::DocComment {
+                style: DocStyle::Inner,
+                text: strip_block_doc_decoration(&chars.into_iter().collect::<String>()),
+            });
         let multi_line = just("/*")
             .then(take_until(just("*/")))
             .ignored()
             .to(Token::Comment);
-        // The comments will get filtered out in the next stage,
-        // but parsing them here to preserve semantic indentation
-        let comment = single_line.or(multi_line).map(TokenTree::Token);
+
+        // Plain comments will get filtered out in the next stage, but parsing
+        // them here preserves semantic indentation; doc comments are kept so
+        // a later item stage can attach them to whatever they document.
+        let comment = outer_line_doc
+            .or(inner_line_doc)
+            .or(single_line)
+            .or(outer_block_doc)
+            .or(inner_block_doc)
+            .or(multi_line)
+            .then(spacing)
+            .map(|(tok, spacing)| TokenTree::Token(tok, spacing));
 
         single
             .or(string)
@@ -158,14 +495,263 @@ fn lexer() -> impl Parser<char, Vec<Spanned<TokenTree>>, Error = Simple<char>> {
             .map_with_span(|tt, span| (tt, span))
     });
 
-    text::semantic_indentation(tt, |tts, span| (TokenTree::Tree(Delim::Block, tts), span))
-        .then_ignore(end())
+    // A close with nothing open to match it against at all (unlike the
+    // close-inside-a-group cases above, there's no opener span to report).
+    // Still consume it and record the mismatch rather than letting the
+    // whole lex abort on the first one it can't place.
+    let top_level_errors = delim_errors;
+    let top_level_stray_close = stray_close.map_with_span(move |(c, close_span), span: Span| {
+        let delim = if c == ')' { Delim::Parenthesis } else { Delim::Curly };
+        top_level_errors.borrow_mut().push(DelimError {
+            delim: delim.clone(),
+            open: None,
+            unexpected_close: Some(close_span),
+        });
+        (TokenTree::Tree(delim, Vec::new()), span)
+    });
+
+    text::semantic_indentation(tt.or(top_level_stray_close), |tts, span| {
+        (TokenTree::Tree(Delim::Block, tts), span)
+    })
+    .then_ignore(end())
+    .map(glue_operators)
 }
 
 fn main() {
     let src = include_str!("hello.an");
-    match lexer().parse(src) {
+    let delim_errors = Rc::new(RefCell::new(Vec::new()));
+    match lexer(delim_errors.clone()).parse(src) {
         Ok(tts) => println!("{:#?}", tts),
         Err(err) => println!("Parse error: {:#?}", err),
     }
+    for err in delim_errors.borrow().iter() {
+        match (&err.open, &err.unexpected_close) {
+            (Some(open), Some(close)) => println!(
+                "Mismatched delimiter: unclosed `{:?}` opened at {:?}, found unexpected close at {:?}",
+                err.delim, open, close
+            ),
+            (Some(open), None) => println!("Unclosed `{:?}` opened at {:?}", err.delim, open),
+            (None, Some(close)) => println!(
+                "Unexpected closing `{:?}` at {:?} with no matching open",
+                err.delim, close
+            ),
+            (None, None) => unreachable!("a DelimError always has an open or a close"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(src: &str) -> Vec<Spanned<TokenTree>> {
+        lexer(Rc::new(RefCell::new(Vec::new())))
+            .parse(src)
+            .expect("lexing should succeed")
+    }
+
+    fn first_token(tts: &[Spanned<TokenTree>]) -> &Token {
+        match tts.first().expect("no tokens produced") {
+            (TokenTree::Token(tok, _), _) => tok,
+            (TokenTree::Tree(_, inner), _) => first_token(inner),
+        }
+    }
+
+    #[test]
+    fn leading_underscore_is_an_identifier_not_an_integer() {
+        assert!(matches!(first_token(&lex("_123")), Token::Ident(name) if name == "_123"));
+    }
+
+    #[test]
+    fn bare_wildcard_is_an_identifier() {
+        assert!(matches!(first_token(&lex("_")), Token::Ident(name) if name == "_"));
+    }
+
+    #[test]
+    fn digit_separators_are_stripped() {
+        assert!(matches!(first_token(&lex("1_000_000")), Token::Int(1_000_000, None)));
+    }
+
+    #[test]
+    fn hex_octal_binary_prefixes() {
+        assert!(matches!(first_token(&lex("0xFF")), Token::Int(255, None)));
+        assert!(matches!(first_token(&lex("0o17")), Token::Int(15, None)));
+        assert!(matches!(first_token(&lex("0b101")), Token::Int(5, None)));
+    }
+
+    #[test]
+    fn float_literals_with_fraction_and_exponent() {
+        match first_token(&lex("1.5")) {
+            Token::Float(value, None) => assert!((*value - 1.5).abs() < f64::EPSILON),
+            other => panic!("expected a float, got {other:?}"),
+        }
+        match first_token(&lex("2.5e-3")) {
+            Token::Float(value, None) => assert!((*value - 2.5e-3).abs() < 1e-12),
+            other => panic!("expected a float, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn member_access_dot_is_not_swallowed_into_a_float() {
+        // `1.method` must lex `1` as an integer, leaving the `.` for
+        // `MemberAccess` rather than treating it as a decimal point.
+        assert!(matches!(first_token(&lex("1.method")), Token::Int(1, None)));
+    }
+
+    #[test]
+    fn outer_line_doc_comment_strips_marker_and_leading_space() {
+        match first_token(&lex("/// hello\na")) {
+            Token::DocComment { style: DocStyle::Outer, text } => assert_eq!(text, "hello"),
+            other => panic!("expected an outer line doc comment, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn inner_line_doc_comment_strips_marker_and_leading_space() {
+        match first_token(&lex("//! hello\na")) {
+            Token::DocComment { style: DocStyle::Inner, text } => assert_eq!(text, "hello"),
+            other => panic!("expected an inner line doc comment, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn outer_block_doc_comment_strips_markers() {
+        match first_token(&lex("/** hello */\na")) {
+            Token::DocComment { style: DocStyle::Outer, text } => assert_eq!(text.trim(), "hello"),
+            other => panic!("expected an outer block doc comment, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn inner_block_doc_comment_strips_markers() {
+        match first_token(&lex("/*! hello */\na")) {
+            Token::DocComment { style: DocStyle::Inner, text } => assert_eq!(text.trim(), "hello"),
+            other => panic!("expected an inner block doc comment, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn quad_slash_falls_back_to_a_plain_comment() {
+        assert!(matches!(first_token(&lex("//// hello\na")), Token::Comment));
+    }
+
+    #[test]
+    fn triple_star_block_comment_falls_back_to_a_plain_comment() {
+        assert!(matches!(first_token(&lex("/*** hello */\na")), Token::Comment));
+    }
+
+    #[test]
+    fn overflowing_integer_is_a_lex_error_not_a_panic() {
+        let errs = lexer(Rc::new(RefCell::new(Vec::new())))
+            .parse("99999999999999999999999999")
+            .unwrap_err();
+        assert!(!errs.is_empty());
+    }
+
+    /// Recursively collects every `Token` out of a tree of `TokenTree`s, in
+    /// order, flattening away the `Block`/`Parenthesis`/etc. nesting.
+    fn flatten_tokens(tts: &[Spanned<TokenTree>]) -> Vec<&Token> {
+        let mut out = Vec::new();
+        for (tt, _) in tts {
+            match tt {
+                TokenTree::Token(tok, _) => out.push(tok),
+                TokenTree::Tree(_, inner) => out.extend(flatten_tokens(inner)),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn glued_equals_equals_is_a_single_equality_operator() {
+        let tts = lex("a==b");
+        let ops: Vec<&Token> = flatten_tokens(&tts)
+            .into_iter()
+            .filter(|tok| matches!(tok, Token::Operator(_)))
+            .collect();
+        assert_eq!(ops.len(), 1);
+        assert!(matches!(ops[0], Token::Operator(Operator::Equality)));
+    }
+
+    #[test]
+    fn spaced_equals_equals_stays_two_assignment_operators() {
+        let tts = lex("a = = b");
+        let ops: Vec<&Token> = flatten_tokens(&tts)
+            .into_iter()
+            .filter(|tok| matches!(tok, Token::Operator(_)))
+            .collect();
+        assert_eq!(ops.len(), 2);
+        assert!(ops.iter().all(|tok| matches!(tok, Token::Operator(Operator::Equals))));
+    }
+
+    #[test]
+    fn glued_plus_equals_is_a_single_operator() {
+        let tts = lex("a+=b");
+        let ops: Vec<&Token> = flatten_tokens(&tts)
+            .into_iter()
+            .filter(|tok| matches!(tok, Token::Operator(_)))
+            .collect();
+        assert_eq!(ops.len(), 1);
+        assert!(matches!(ops[0], Token::Operator(Operator::AddAssign)));
+    }
+
+    #[test]
+    fn glued_dot_dot_is_a_single_range_operator() {
+        let tts = lex("a..b");
+        let ops: Vec<&Token> = flatten_tokens(&tts)
+            .into_iter()
+            .filter(|tok| matches!(tok, Token::Operator(_)))
+            .collect();
+        assert_eq!(ops.len(), 1);
+        assert!(matches!(ops[0], Token::Operator(Operator::Range)));
+    }
+
+    #[test]
+    fn mismatched_close_at_top_level_recovers_instead_of_hard_failing() {
+        let delim_errors = Rc::new(RefCell::new(Vec::new()));
+        let tts = lexer(delim_errors.clone())
+            .parse("(a}")
+            .expect("a stray close should recover rather than fail the whole lex");
+        assert_eq!(delim_errors.borrow().len(), 1);
+        assert_eq!(delim_errors.borrow()[0].delim, Delim::Parenthesis);
+        assert!(delim_errors.borrow()[0].unexpected_close.is_some());
+        // The stray `}` was consumed as the (mismatched) close, so nothing
+        // is left dangling after the parenthesized group.
+        assert!(matches!(first_token(&tts), Token::Ident(name) if name == "a"));
+    }
+
+    #[test]
+    fn a_properly_closed_group_does_not_also_swallow_the_next_close() {
+        // A parenthesized expression immediately followed by the
+        // interpolation's own closing `}`, with no space in between: the
+        // paren group's own close must not eat the `}` that belongs to the
+        // enclosing interpolation.
+        let delim_errors = Rc::new(RefCell::new(Vec::new()));
+        lexer(delim_errors.clone())
+            .parse(r#""${(a)}""#)
+            .expect("a balanced interpolation should lex cleanly");
+        assert!(delim_errors.borrow().is_empty());
+    }
+
+    #[test]
+    fn orphan_close_with_nothing_enclosing_it_recovers_instead_of_hard_failing() {
+        let delim_errors = Rc::new(RefCell::new(Vec::new()));
+        lexer(delim_errors.clone())
+            .parse(")")
+            .expect("an orphan close should recover rather than fail the whole lex");
+        assert_eq!(delim_errors.borrow().len(), 1);
+        assert_eq!(delim_errors.borrow()[0].delim, Delim::Parenthesis);
+        assert!(delim_errors.borrow()[0].open.is_none());
+        assert!(delim_errors.borrow()[0].unexpected_close.is_some());
+    }
+
+    #[test]
+    fn orphan_close_after_a_real_token_still_recovers() {
+        let delim_errors = Rc::new(RefCell::new(Vec::new()));
+        let tts = lexer(delim_errors.clone())
+            .parse("a)")
+            .expect("an orphan close after a token should recover rather than fail the whole lex");
+        assert_eq!(delim_errors.borrow().len(), 1);
+        assert!(delim_errors.borrow()[0].open.is_none());
+        assert!(matches!(first_token(&tts), Token::Ident(name) if name == "a"));
+    }
 }